@@ -10,10 +10,16 @@ use glib;
 use gtk::prelude::*;
 use gtk::{ApplicationWindow, Builder, Overlay};
 
+use crate::lyrics::parse_lrc;
+use crate::mpris::MprisServer;
+use crate::fallback::FallbackConfig;
+use crate::notify::Notifier;
+use crate::queue::{PlayQueue, RepeatMode, ShuffleMode};
+use crate::worker::{PageTracker, WorkerPool};
 use crate::musicapi::model::{LoginInfo, SongInfo, SongList};
 use crate::utils::PlayerTypes;
 use crate::view::*;
-use crate::widgets::{header::*, mark_all_notif, notice::*, player::*};
+use crate::widgets::{header::*, lyrics_view::LyricsView, mark_all_notif, notice::*, player::*};
 use std::cell::RefCell;
 use std::env;
 use std::rc::Rc;
@@ -55,6 +61,23 @@ pub(crate) enum Action {
     Logout,
     ShowNotice(String),
     DailyTask,
+    WindowPresent,
+    PlayerNext,
+    PlayerPrevious,
+    PlayerPlayPause,
+    PlayerStop,
+    PlayerSeek(i64),
+    PlayerSetPosition(String, i64),
+    RefreshLyrics(Vec<(u64, String)>),
+    DownloadSong(SongInfo),
+    DownloadSongList(Vec<SongInfo>),
+    SetRepeatMode(RepeatMode),
+    SetShuffleMode(ShuffleMode),
+    RestoreQueue,
+    /// Sent by `PlayerWrapper` when the stream it was handed fails to play
+    /// (expired link, 403, decode error, ...), so an alternate source can
+    /// be tried instead of leaving the track silently stuck.
+    PlayerError(SongInfo),
 }
 
 #[derive(Clone)]
@@ -63,10 +86,17 @@ pub(crate) struct App {
     view: Rc<View>,
     header: Rc<Header>,
     player: PlayerWrapper,
+    lyrics_view: Rc<LyricsView>,
     notice: RefCell<Option<InAppNotification>>,
     overlay: Overlay,
     sender: Sender<Action>,
     receiver: Receiver<Action>,
+    mpris: Option<MprisServer>,
+    notifier: Option<Arc<Notifier>>,
+    worker: WorkerPool,
+    found_page_tracker: PageTracker,
+    queue: RefCell<PlayQueue>,
+    fallback_config: FallbackConfig,
 }
 
 impl App {
@@ -87,6 +117,7 @@ impl App {
         let view = View::new(&builder, &sender, data.clone());
         let header = Header::new(&builder, &sender, data.clone());
         let player = PlayerWrapper::new(&builder, &sender, data.clone());
+        let lyrics_view = LyricsView::new(&builder);
 
         window.show_all();
 
@@ -106,22 +137,207 @@ impl App {
 
         let notice = RefCell::new(None);
 
+        let mpris = MprisServer::new(sender.clone())
+            .map_err(|e| error!("Failed to register MPRIS2 D-Bus interface: {}", e))
+            .ok();
+
+        let notifier = Notifier::new(sender.clone())
+            .map_err(|e| warn!("Desktop notifications unavailable, falling back to in-app notices: {}", e))
+            .ok()
+            .map(Arc::new);
+
+        let worker = WorkerPool::new();
+        let found_page_tracker = PageTracker::default();
+        let queue = RefCell::new(PlayQueue::load().unwrap_or_default());
+        let has_saved_queue = queue.borrow().current().is_some();
+        let restore_sender = sender.clone();
+        let fallback_config = crate::utils::get_config()
+            .and_then(|cfg| cfg.fallback)
+            .unwrap_or_default();
+
         let app = App {
             window,
             header,
             view,
             player,
+            lyrics_view,
             notice,
             overlay,
             sender,
             receiver,
+            mpris,
+            notifier,
+            worker,
+            found_page_tracker,
+            queue,
+            fallback_config,
         };
+
+        if has_saved_queue {
+            restore_sender
+                .send(Action::RestoreQueue)
+                .unwrap_or_else(|e| error!("Failed to request queue restore: {}", e));
+        }
+
         Rc::new(app)
     }
 
+    /// Fetches the LRC lyric for `song` on the worker pool and delivers it
+    /// as `Action::RefreshLyrics`, so the HTTP call never blocks the GTK
+    /// main loop.
+    fn fetch_lyrics(&self, song: &SongInfo) {
+        let sender = self.sender.clone();
+        let id = song.id;
+        self.worker.submit(move || match crate::musicapi::MusicApi::lyric(id) {
+            Ok(raw) => {
+                let lines: Vec<(u64, String)> = parse_lrc(&raw)
+                    .into_iter()
+                    .map(|(ts, text)| (ts.as_millis() as u64, text))
+                    .collect();
+                sender
+                    .send(Action::RefreshLyrics(lines))
+                    .unwrap_or_else(|e| error!("Failed to deliver lyrics: {}", e));
+            }
+            Err(e) => error!("Failed to fetch lyric for song {}: {}", id, e),
+        });
+    }
+
+    /// Shows a desktop notification for a track change on the worker pool
+    /// (it downloads and caches the cover art over HTTP), falling back to
+    /// the in-app overlay notice when the D-Bus service is unavailable.
+    fn notify_track_change(&self, song: &SongInfo) {
+        let notifier = self.notifier.clone();
+        let sender = self.sender.clone();
+        let song = song.clone();
+        self.worker.submit(move || {
+            let notified = match &notifier {
+                Some(n) => match n.notify_track_change(&song) {
+                    Ok(()) => true,
+                    Err(e) => {
+                        warn!("Desktop notification failed, falling back: {}", e);
+                        false
+                    }
+                },
+                None => false,
+            };
+
+            if !notified {
+                sender
+                    .send(Action::ShowNotice(format!("{} - {}", song.singer, song.name)))
+                    .unwrap_or_else(|e| error!("Failed to deliver fallback notice: {}", e));
+            }
+        });
+    }
+
+    /// Advances the persistent queue (respecting repeat/shuffle) and plays
+    /// whatever it lands on, if anything.
+    fn advance_queue(&self) {
+        let next = {
+            let mut queue = self.queue.borrow_mut();
+            let next = queue.advance().cloned();
+            queue.save();
+            next
+        };
+        if let Some(song) = next {
+            self.resolve_and_play(song, 0);
+        }
+    }
+
+    /// Moves the persistent queue back one step and plays the result.
+    fn retreat_queue(&self) {
+        let prev = {
+            let mut queue = self.queue.borrow_mut();
+            let prev = queue.retreat().cloned();
+            queue.save();
+            prev
+        };
+        if let Some(song) = prev {
+            self.resolve_and_play(song, 0);
+        }
+    }
+
+    /// Resolves a playable URL for `song` on the worker pool, then starts
+    /// playback and (if `seek_ms` is non-zero) seeks to the saved position.
+    fn resolve_and_play(&self, song: SongInfo, seek_ms: u64) {
+        let sender = self.sender.clone();
+        self.worker.submit(move || match crate::musicapi::MusicApi::song_url(song.id) {
+            Ok(url) => {
+                sender
+                    .send(Action::Player(song, url))
+                    .unwrap_or_else(|e| error!("worker: failed to deliver Player action: {}", e));
+                if seek_ms > 0 {
+                    sender
+                        .send(Action::PlayerSeek((seek_ms * 1000) as i64))
+                        .unwrap_or_else(|e| error!("worker: failed to deliver resume seek: {}", e));
+                }
+            }
+            Err(e) => error!("Failed to resolve playback URL: {}", e),
+        });
+    }
+
+    /// Keeps the persisted queue's "current song" pointer in sync with
+    /// whatever just started playing, so Next/Previous/resume stay correct
+    /// even for tracks started from outside the queue (search, a playlist
+    /// row, FM). If `song` isn't the queue's current track, the queue is
+    /// replaced by a fresh single-track queue holding it.
+    fn sync_queue_current(&self, song: &SongInfo) {
+        let mut queue = self.queue.borrow_mut();
+        if queue.current().map(|s| s.id) != Some(song.id) {
+            *queue = PlayQueue::new(vec![song.clone()]);
+        }
+        queue.save();
+    }
+
+    /// Looks for a playable alternate-source URL when Netease didn't give
+    /// us one (a "grey", copyright-restricted track) or when the URL we
+    /// were given failed to actually play (`Action::PlayerError`), and
+    /// surfaces which source ends up playing.
+    fn resolve_fallback(&self, song: SongInfo) {
+        if self.fallback_config.providers.is_empty() {
+            self.sender
+                .send(Action::ShowNotice(format!("{} 暂时无法播放", song.name)))
+                .unwrap_or_else(|e| error!("Failed to deliver unplayable notice: {}", e));
+            return;
+        }
+
+        let sender = self.sender.clone();
+        let config = self.fallback_config.clone();
+        self.worker.submit(move || match crate::fallback::resolve(&song, &config) {
+            Some((provider, url)) => {
+                sender
+                    .send(Action::ShowNotice(format!(
+                        "{} 暂无版权，改用 {} 播放",
+                        song.name,
+                        provider.display_name()
+                    )))
+                    .unwrap_or_else(|e| error!("Failed to deliver fallback source notice: {}", e));
+                sender
+                    .send(Action::Player(song, url))
+                    .unwrap_or_else(|e| error!("Failed to deliver fallback Player action: {}", e));
+            }
+            None => {
+                sender
+                    .send(Action::ShowNotice(format!("{} 暂时无法播放", song.name)))
+                    .unwrap_or_else(|e| error!("Failed to deliver unplayable notice: {}", e));
+            }
+        });
+    }
+
     fn init(app: &Rc<Self>) {
         // Setup the Action channel
         gtk::timeout_add(25, crate::clone!(app => move || app.setup_action_channel()));
+        // Keeps the lyrics panel scrolled/highlighted in time with playback.
+        gtk::timeout_add(250, crate::clone!(app => move || app.tick_lyrics()));
+    }
+
+    /// Polls the current playback position and advances the lyrics panel's
+    /// highlighted line to match, so it scrolls along with the song instead
+    /// of sitting static once fetched.
+    fn tick_lyrics(&self) -> glib::Continue {
+        if let Some(position) = self.player.position() {
+            self.lyrics_view.tick(position);
+        }
+        glib::Continue(true)
     }
 
     fn setup_action_channel(&self) -> glib::Continue {
@@ -151,7 +367,24 @@ impl App {
             Action::SwitchStackSub((id, name, image_path)) => {
                 self.view.switch_stack_sub(id, name, image_path)
             }
-            Action::RefreshFoundViewInit(id) => self.view.update_found_view_data(id),
+            Action::RefreshFoundViewInit(id) => {
+                // One key for the whole found view, so a late response for a
+                // page the user already moved away from is dropped.
+                const FOUND_VIEW: u8 = 0;
+                let generation = self.found_page_tracker.begin(FOUND_VIEW);
+                let sender = self.sender.clone();
+                let tracker = self.found_page_tracker.clone();
+                self.worker.submit(move || match crate::musicapi::MusicApi::song_list_detail(id) {
+                    Ok(song_list) => {
+                        if tracker.is_current(FOUND_VIEW, generation) {
+                            sender
+                                .send(Action::RefreshFoundView(song_list))
+                                .unwrap_or_else(|e| error!("worker: failed to deliver RefreshFoundView: {}", e));
+                        }
+                    }
+                    Err(e) => error!("Failed to refresh found view {}: {}", id, e),
+                });
+            }
             Action::RefreshFoundView(song_list) => self.view.update_found_view(song_list),
             Action::RefreshMine => self.view.mine_init(),
             Action::MineHideAll => self.view.mine_hide_all(),
@@ -173,11 +406,60 @@ impl App {
             }
             Action::CancelCollection => self.view.cancel_collection(),
             Action::Search(text) => self.view.switch_stack_search(text),
-            Action::Login(name, pass) => self.header.login(name, pass),
-            Action::Logout => self.header.logout(),
-            Action::DailyTask => self.header.daily_task(),
+            Action::Login(name, pass) => {
+                let sender = self.sender.clone();
+                self.worker.submit(move || match crate::musicapi::MusicApi::login(&name, &pass) {
+                    Ok(login_info) => {
+                        sender
+                            .send(Action::RefreshHeaderUserLogin(login_info))
+                            .unwrap_or_else(|e| error!("worker: failed to deliver login result: {}", e));
+                    }
+                    Err(e) => {
+                        sender
+                            .send(Action::ShowNotice(format!("登录失败: {}", e)))
+                            .unwrap_or_else(|e| error!("worker: failed to deliver login error: {}", e));
+                    }
+                });
+            }
+            Action::Logout => {
+                let sender = self.sender.clone();
+                self.worker.submit(move || {
+                    crate::musicapi::MusicApi::logout();
+                    sender
+                        .send(Action::RefreshHeaderUserLogout)
+                        .unwrap_or_else(|e| error!("worker: failed to deliver logout result: {}", e));
+                });
+            }
+            Action::DailyTask => {
+                let sender = self.sender.clone();
+                self.worker.submit(move || {
+                    if let Err(e) = crate::musicapi::MusicApi::daily_task() {
+                        error!("Daily task failed: {}", e);
+                    }
+                });
+            }
             Action::PlayerInit(info, pt) => self.player.initialize_player(info, pt),
-            Action::Player(info, url) => self.player.player(info, url),
+            Action::Player(info, url) => {
+                if url.is_empty() {
+                    self.resolve_fallback(info);
+                    return glib::Continue(true);
+                }
+
+                self.notify_track_change(&info);
+                self.fetch_lyrics(&info);
+                self.sync_queue_current(&info);
+
+                if let Some(mpris) = &self.mpris {
+                    let queue = self.queue.borrow();
+                    mpris.update(
+                        &info,
+                        crate::mpris::PlaybackStatus::Playing,
+                        queue.can_advance(),
+                        queue.can_retreat(),
+                    );
+                }
+                self.player.player(info, url)
+            }
             Action::ShowNotice(text) => {
                 let notif = mark_all_notif(text);
                 let old = self.notice.replace(Some(notif));
@@ -187,6 +469,58 @@ impl App {
             Action::PlayerSubpages => self.view.play_subpages(),
             Action::PlayerFound => self.view.play_found(),
             Action::PlayerMine => self.view.play_mine(),
+            Action::WindowPresent => self.window.present(),
+            Action::PlayerNext => self.advance_queue(),
+            Action::PlayerPrevious => self.retreat_queue(),
+            Action::PlayerPlayPause => {
+                let status = self.player.play_pause();
+                if let Some(mpris) = &self.mpris {
+                    mpris.update_status(status);
+                }
+            }
+            Action::PlayerStop => {
+                let status = self.player.stop();
+                if let Some(mpris) = &self.mpris {
+                    mpris.update_status(status);
+                }
+            }
+            Action::PlayerSeek(offset_us) => self.player.seek_relative(offset_us),
+            Action::PlayerSetPosition(track_id, position_us) => {
+                self.player.set_position(track_id, position_us)
+            }
+            Action::RefreshLyrics(lines) => self.lyrics_view.update_lyrics(lines),
+            Action::DownloadSong(song) => {
+                let sender = self.sender.clone();
+                let dest_dir = download_dir();
+                std::thread::spawn(move || crate::download::download_song(song, dest_dir, sender));
+            }
+            Action::DownloadSongList(songs) => {
+                let sender = self.sender.clone();
+                let dest_dir = download_dir();
+                std::thread::spawn(move || {
+                    crate::download::download_song_list(songs, dest_dir, sender)
+                });
+            }
+            Action::SetRepeatMode(mode) => {
+                let mut queue = self.queue.borrow_mut();
+                queue.set_repeat(mode);
+                queue.save();
+            }
+            Action::SetShuffleMode(mode) => {
+                let mut queue = self.queue.borrow_mut();
+                queue.set_shuffle(mode);
+                queue.save();
+            }
+            Action::RestoreQueue => {
+                let (song, last_position_ms) = {
+                    let queue = self.queue.borrow();
+                    (queue.current().cloned(), queue.last_position_ms)
+                };
+                if let Some(song) = song {
+                    self.resolve_and_play(song, last_position_ms);
+                }
+            }
+            Action::PlayerError(song) => self.resolve_fallback(song),
         }
 
         glib::Continue(true)
@@ -229,3 +563,15 @@ impl App {
         ApplicationExtManual::run(&application, &args);
     }
 }
+
+/// Where downloaded tracks are written by default; configurable by the
+/// user via the `[download] path` key in the app config.
+fn download_dir() -> std::path::PathBuf {
+    crate::utils::get_config()
+        .and_then(|cfg| cfg.download_path)
+        .unwrap_or_else(|| {
+            glib::get_user_special_dir(glib::UserDirectory::Music)
+                .unwrap_or_else(|| glib::get_home_dir().join("Music"))
+                .join("netease-cloud-music-gtk")
+        })
+}
@@ -0,0 +1,166 @@
+//
+// download.rs
+// Copyright (C) 2019 gmg137 <gmg137@live.com>
+// Distributed under terms of the GPLv3 license.
+//
+// Downloads tracks and tags the resulting file with metadata, cover art
+// and synchronized lyrics.
+//
+use crate::musicapi::model::SongInfo;
+use crate::musicapi::MusicApi;
+use crossbeam_channel::Sender;
+use lofty::{Accessor, AudioFile, ItemKey, Picture, PictureType, Tag, TagExt, TaggedFileExt};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::app::Action;
+
+/// Downloads `song`, tags the resulting file, and reports progress/errors
+/// back to the UI via `Action::ShowNotice`.
+pub(crate) fn download_song(song: SongInfo, dest_dir: PathBuf, sender: Sender<Action>) {
+    if let Err(e) = download_song_inner(&song, &dest_dir) {
+        sender
+            .send(Action::ShowNotice(format!(
+                "下载失败: {} - {}",
+                song.name, e
+            )))
+            .unwrap_or_else(|e| error!("download: failed to deliver notice: {}", e));
+        return;
+    }
+
+    sender
+        .send(Action::ShowNotice(format!("下载完成: {}", song.name)))
+        .unwrap_or_else(|e| error!("download: failed to deliver notice: {}", e));
+}
+
+/// Downloads every song in `list` sequentially, reporting each as it finishes.
+pub(crate) fn download_song_list(list: Vec<SongInfo>, dest_dir: PathBuf, sender: Sender<Action>) {
+    for song in list {
+        download_song(song, dest_dir.clone(), sender.clone());
+    }
+}
+
+fn download_song_inner(song: &SongInfo, dest_dir: &Path) -> anyhow::Result<()> {
+    fs::create_dir_all(dest_dir)?;
+
+    let url = MusicApi::song_url(song.id)?;
+    let audio = MusicApi::download(&url)?;
+
+    let ext = guess_extension(&audio);
+    let dest = dest_dir.join(format!("{} - {}.{}", song.singer, song.name, ext));
+    fs::write(&dest, &audio)?;
+
+    let cover = MusicApi::download(&song.pic_url).ok();
+    let lyric = MusicApi::lyric(song.id).ok();
+
+    tag_file(&dest, song, cover.as_deref(), lyric.as_deref())?;
+
+    Ok(())
+}
+
+/// Sniffs the handful of container types Netease actually serves.
+fn guess_extension(data: &[u8]) -> &'static str {
+    if data.starts_with(b"fLaC") {
+        "flac"
+    } else if data.starts_with(b"OggS") {
+        "ogg"
+    } else if data.starts_with(b"ID3") || (data.len() > 1 && data[0] == 0xFF && data[1] & 0xE0 == 0xE0) {
+        "mp3"
+    } else if data.len() > 8 && &data[4..8] == b"ftyp" {
+        "m4a"
+    } else {
+        "mp3"
+    }
+}
+
+/// Sniffs a cover image's MIME type from its own bytes, since it almost
+/// always differs from the audio file it's being embedded into.
+fn guess_picture_mime_type(data: &[u8]) -> lofty::MimeType {
+    if data.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        lofty::MimeType::Jpeg
+    } else if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+        lofty::MimeType::Png
+    } else if data.starts_with(b"GIF87a") || data.starts_with(b"GIF89a") {
+        lofty::MimeType::Gif
+    } else if data.starts_with(b"BM") {
+        lofty::MimeType::Bmp
+    } else {
+        lofty::MimeType::Jpeg
+    }
+}
+
+/// Writes title/artist/album, embeds the cover and synchronized lyrics
+/// into `path` using the tag format native to its container (ID3v2 for
+/// MP3, Vorbis comments for FLAC/OGG, MP4 atoms for M4A).
+fn tag_file(
+    path: &Path,
+    song: &SongInfo,
+    cover: Option<&[u8]>,
+    lyric: Option<&str>,
+) -> anyhow::Result<()> {
+    let mut tagged_file = lofty::read_from_path(path)?;
+    let tag_type = tagged_file.primary_tag_type();
+
+    if tagged_file.tag(tag_type).is_none() {
+        tagged_file.insert_tag(Tag::new(tag_type));
+    }
+    let tag = tagged_file.tag_mut(tag_type).expect("tag just inserted");
+
+    tag.set_title(song.name.clone());
+    tag.set_artist(song.singer.clone());
+    tag.set_album(song.album.clone());
+
+    if let Some(cover) = cover {
+        let picture = Picture::new_unchecked(
+            PictureType::CoverFront,
+            guess_picture_mime_type(cover),
+            None,
+            cover.to_vec(),
+        );
+        tag.set_picture(0, picture);
+    }
+
+    if let Some(lyric) = lyric {
+        // Stored as the fetched LRC text, timestamps included.
+        tag.insert_text(ItemKey::Lyrics, lyric.to_owned());
+    }
+
+    tag.save_to_path(path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sniffs_flac_ogg_mp3_and_m4a() {
+        assert_eq!(guess_extension(b"fLaC\x00\x00\x00\x22"), "flac");
+        assert_eq!(guess_extension(b"OggS\x00\x02\x00\x00"), "ogg");
+        assert_eq!(guess_extension(b"ID3\x04\x00\x00\x00\x00\x00\x00"), "mp3");
+        assert_eq!(guess_extension(&[0xFF, 0xFB, 0x90, 0x00]), "mp3");
+        let mut m4a = vec![0u8; 4];
+        m4a.extend_from_slice(b"ftypM4A ");
+        assert_eq!(guess_extension(&m4a), "m4a");
+    }
+
+    #[test]
+    fn unknown_container_falls_back_to_mp3() {
+        assert_eq!(guess_extension(b"\x00\x00\x00\x00"), "mp3");
+    }
+
+    #[test]
+    fn sniffs_picture_mime_type_from_its_own_bytes() {
+        assert_eq!(
+            guess_picture_mime_type(&[0xFF, 0xD8, 0xFF, 0xE0]),
+            lofty::MimeType::Jpeg
+        );
+        assert_eq!(
+            guess_picture_mime_type(b"\x89PNG\r\n\x1a\nabc"),
+            lofty::MimeType::Png
+        );
+        assert_eq!(guess_picture_mime_type(b"GIF89aabc"), lofty::MimeType::Gif);
+        assert_eq!(guess_picture_mime_type(b"BMabc"), lofty::MimeType::Bmp);
+        assert_eq!(guess_picture_mime_type(b"\x00\x00\x00\x00"), lofty::MimeType::Jpeg);
+    }
+}
@@ -0,0 +1,110 @@
+//
+// fallback.rs
+// Copyright (C) 2019 gmg137 <gmg137@live.com>
+// Distributed under terms of the GPLv3 license.
+//
+// Netease frequently returns no playable URL for copyright-restricted
+// ("grey") tracks. This resolves an alternate source by searching other
+// providers for the same title/artist and picking the closest duration
+// match, so the user isn't left with a silently unplayable song.
+//
+use crate::musicapi::model::SongInfo;
+use crate::musicapi::MusicApi;
+
+/// An alternate streaming provider that can be searched as a fallback.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub(crate) enum AltProvider {
+    Migu,
+    Kugou,
+}
+
+impl AltProvider {
+    pub(crate) fn display_name(self) -> &'static str {
+        match self {
+            AltProvider::Migu => "咪咕音乐",
+            AltProvider::Kugou => "酷狗音乐",
+        }
+    }
+}
+
+/// User-configurable fallback behaviour: which providers to try, in what
+/// order. An empty list disables fallbacks entirely.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub(crate) struct FallbackConfig {
+    pub(crate) providers: Vec<AltProvider>,
+}
+
+impl Default for FallbackConfig {
+    fn default() -> Self {
+        FallbackConfig {
+            providers: vec![AltProvider::Migu, AltProvider::Kugou],
+        }
+    }
+}
+
+/// A candidate track returned by an alternate provider's search.
+struct AltCandidate {
+    url: String,
+    duration: u64,
+}
+
+/// Searches `config.providers` in order for `song` and returns the
+/// duration-matched URL from the first provider with a usable result,
+/// along with which provider it came from (for the "now playing via X"
+/// notice).
+pub(crate) fn resolve(song: &SongInfo, config: &FallbackConfig) -> Option<(AltProvider, String)> {
+    for &provider in &config.providers {
+        let candidates = match MusicApi::search_alternate(provider, &song.name, &song.singer) {
+            Ok(c) => c,
+            Err(e) => {
+                error!("fallback: {} search failed: {}", provider.display_name(), e);
+                continue;
+            }
+        };
+
+        if let Some(best) = best_duration_match(&candidates, song.duration) {
+            return Some((provider, best.url.clone()));
+        }
+    }
+    None
+}
+
+/// Picks the candidate whose duration is closest to `target_ms`.
+fn best_duration_match(candidates: &[AltCandidate], target_ms: u64) -> Option<&AltCandidate> {
+    candidates.iter().min_by_key(|c| c.duration.abs_diff(target_ms))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candidate(url: &str, duration: u64) -> AltCandidate {
+        AltCandidate {
+            url: url.to_owned(),
+            duration,
+        }
+    }
+
+    #[test]
+    fn picks_the_closest_duration_match() {
+        let candidates = vec![
+            candidate("a", 180_000),
+            candidate("b", 210_000),
+            candidate("c", 200_500),
+        ];
+        let best = best_duration_match(&candidates, 200_000).unwrap();
+        assert_eq!(best.url, "c");
+    }
+
+    #[test]
+    fn exact_match_wins_outright() {
+        let candidates = vec![candidate("a", 150_000), candidate("b", 200_000)];
+        let best = best_duration_match(&candidates, 200_000).unwrap();
+        assert_eq!(best.url, "b");
+    }
+
+    #[test]
+    fn empty_candidate_list_yields_no_match() {
+        assert!(best_duration_match(&[], 200_000).is_none());
+    }
+}
@@ -0,0 +1,127 @@
+//
+// lyrics.rs
+// Copyright (C) 2019 gmg137 <gmg137@live.com>
+// Distributed under terms of the GPLv3 license.
+//
+// Parsing and time-lookup for synchronized (LRC) lyrics.
+//
+use std::time::Duration;
+
+/// One parsed LRC line: the timestamp it starts at, and its text.
+pub(crate) type LyricLine = (Duration, String);
+
+/// Parses an LRC document into a time-sorted list of `(timestamp, text)`.
+///
+/// Lines may carry more than one timestamp tag (e.g. `[00:12.00][00:45.00]text`),
+/// in which case the text is duplicated for each timestamp. ID tags such as
+/// `[ti:]`, `[ar:]`, `[by:]`, `[al:]` and unparsable lines are skipped.
+/// Duplicate timestamps preserve their original insertion order (a stable
+/// sort is used), and an LRC with no timed lines at all yields an empty
+/// `Vec`, which callers should treat as "plain, non-scrolling lyrics".
+pub(crate) fn parse_lrc(raw: &str) -> Vec<LyricLine> {
+    let mut lines = Vec::new();
+
+    for line in raw.lines() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let mut rest = line;
+        let mut timestamps = Vec::new();
+
+        while rest.starts_with('[') {
+            let end = match rest.find(']') {
+                Some(i) => i,
+                None => break,
+            };
+            let tag = &rest[1..end];
+            if let Some(ts) = parse_timestamp(tag) {
+                timestamps.push(ts);
+            }
+            rest = &rest[end + 1..];
+        }
+
+        if timestamps.is_empty() {
+            continue;
+        }
+
+        let text = rest.trim().to_owned();
+        for ts in timestamps {
+            lines.push((ts, text.clone()));
+        }
+    }
+
+    lines.sort_by_key(|(ts, _)| *ts);
+    lines
+}
+
+/// Parses a single `mm:ss.xx` (or `mm:ss`) tag; returns `None` for anything
+/// that isn't a timestamp, such as `ti`, `ar`, `by`, `al`, `offset`.
+fn parse_timestamp(tag: &str) -> Option<Duration> {
+    let (minutes, rest) = tag.split_once(':')?;
+    let minutes: u64 = minutes.trim().parse().ok()?;
+    let seconds: f64 = rest.trim().parse().ok()?;
+    if seconds < 0.0 {
+        return None;
+    }
+    Some(Duration::from_secs(minutes * 60) + Duration::from_secs_f64(seconds))
+}
+
+/// Finds the index of the lyric line active at `position`: the greatest
+/// entry whose timestamp is `<= position`. Returns `None` before the first
+/// line starts.
+pub(crate) fn current_line(lines: &[LyricLine], position: Duration) -> Option<usize> {
+    match lines.binary_search_by(|(ts, _)| ts.cmp(&position)) {
+        Ok(i) => Some(i),
+        Err(0) => None,
+        Err(i) => Some(i - 1),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_simple_lines() {
+        let lrc = "[ti:Title]\n[ar:Artist]\n[00:01.00]first\n[00:02.50]second";
+        let parsed = parse_lrc(lrc);
+        assert_eq!(
+            parsed,
+            vec![
+                (Duration::from_millis(1000), "first".to_owned()),
+                (Duration::from_millis(2500), "second".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn handles_multiple_timestamps_per_line() {
+        let lrc = "[00:10.00][00:20.00]chorus";
+        let parsed = parse_lrc(lrc);
+        assert_eq!(
+            parsed,
+            vec![
+                (Duration::from_secs(10), "chorus".to_owned()),
+                (Duration::from_secs(20), "chorus".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn empty_or_untimed_lyrics_yield_no_lines() {
+        assert!(parse_lrc("").is_empty());
+        assert!(parse_lrc("[ti:Title]\n[by:someone]").is_empty());
+    }
+
+    #[test]
+    fn current_line_binary_searches() {
+        let lines = parse_lrc("[00:01.00]a\n[00:02.00]b\n[00:03.00]c");
+        assert_eq!(current_line(&lines, Duration::from_millis(500)), None);
+        assert_eq!(current_line(&lines, Duration::from_secs(1)), Some(0));
+        assert_eq!(current_line(&lines, Duration::from_millis(1500)), Some(0));
+        assert_eq!(current_line(&lines, Duration::from_secs(3)), Some(2));
+        assert_eq!(current_line(&lines, Duration::from_secs(10)), Some(2));
+    }
+}
@@ -0,0 +1,311 @@
+//
+// mpris.rs
+// Copyright (C) 2019 gmg137 <gmg137@live.com>
+// Distributed under terms of the GPLv3 license.
+//
+// MPRIS2 (org.mpris.MediaPlayer2) integration so desktop shells, lock
+// screens and media-key daemons can control playback via D-Bus.
+//
+use crate::musicapi::model::SongInfo;
+use crossbeam_channel::Sender;
+use std::sync::{Arc, Mutex};
+use zbus::{dbus_interface, fdo, ConnectionBuilder, SignalContext};
+use zbus::zvariant::Value;
+
+use crate::app::Action;
+
+const BUS_NAME: &str = "org.mpris.MediaPlayer2.netease_cloud_music_gtk";
+const OBJECT_PATH: &str = "/org/mpris/MediaPlayer2";
+
+#[derive(Debug, Clone, Default)]
+pub(crate) struct MprisMetadata {
+    pub(crate) track_id: String,
+    pub(crate) title: String,
+    pub(crate) artist: String,
+    pub(crate) album: String,
+    pub(crate) art_url: String,
+    pub(crate) length_us: i64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum PlaybackStatus {
+    Playing,
+    Paused,
+    Stopped,
+}
+
+impl PlaybackStatus {
+    fn as_str(self) -> &'static str {
+        match self {
+            PlaybackStatus::Playing => "Playing",
+            PlaybackStatus::Paused => "Paused",
+            PlaybackStatus::Stopped => "Stopped",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct MprisState {
+    pub(crate) metadata: MprisMetadata,
+    pub(crate) status: PlaybackStatus,
+    pub(crate) can_go_next: bool,
+    pub(crate) can_go_previous: bool,
+}
+
+impl Default for MprisState {
+    fn default() -> Self {
+        MprisState {
+            metadata: MprisMetadata::default(),
+            status: PlaybackStatus::Stopped,
+            can_go_next: true,
+            can_go_previous: true,
+        }
+    }
+}
+
+struct MediaPlayer2 {
+    sender: Sender<Action>,
+}
+
+#[dbus_interface(name = "org.mpris.MediaPlayer2")]
+impl MediaPlayer2 {
+    #[dbus_interface(property)]
+    fn identity(&self) -> String {
+        "网易云音乐".to_owned()
+    }
+
+    #[dbus_interface(property)]
+    fn desktop_entry(&self) -> String {
+        "netease-cloud-music-gtk".to_owned()
+    }
+
+    #[dbus_interface(property)]
+    fn can_raise(&self) -> bool {
+        true
+    }
+
+    #[dbus_interface(property)]
+    fn can_quit(&self) -> bool {
+        false
+    }
+
+    #[dbus_interface(property)]
+    fn has_track_list(&self) -> bool {
+        false
+    }
+
+    fn raise(&self) {
+        self.sender
+            .send(Action::WindowPresent)
+            .unwrap_or_else(|e| error!("mpris: failed to send WindowPresent: {}", e));
+    }
+
+    fn quit(&self) {}
+}
+
+struct Player {
+    sender: Sender<Action>,
+    state: Arc<Mutex<MprisState>>,
+}
+
+#[dbus_interface(name = "org.mpris.MediaPlayer2.Player")]
+impl Player {
+    fn next(&self) {
+        self.send(Action::PlayerNext);
+    }
+
+    fn previous(&self) {
+        self.send(Action::PlayerPrevious);
+    }
+
+    fn pause(&self) {
+        self.send(Action::PlayerPlayPause);
+    }
+
+    #[dbus_interface(name = "PlayPause")]
+    fn play_pause(&self) {
+        self.send(Action::PlayerPlayPause);
+    }
+
+    fn stop(&self) {
+        self.send(Action::PlayerStop);
+    }
+
+    fn play(&self) {
+        self.send(Action::PlayerPlayPause);
+    }
+
+    fn seek(&self, offset_us: i64) {
+        self.send(Action::PlayerSeek(offset_us));
+    }
+
+    fn set_position(&self, track_id: zbus::zvariant::ObjectPath<'_>, position_us: i64) {
+        self.send(Action::PlayerSetPosition(track_id.to_string(), position_us));
+    }
+
+    #[dbus_interface(property)]
+    fn playback_status(&self) -> String {
+        self.state.lock().unwrap().status.as_str().to_owned()
+    }
+
+    #[dbus_interface(property)]
+    fn loop_status(&self) -> String {
+        "None".to_owned()
+    }
+
+    #[dbus_interface(property)]
+    fn rate(&self) -> f64 {
+        1.0
+    }
+
+    #[dbus_interface(property)]
+    fn metadata(&self) -> std::collections::HashMap<String, Value<'_>> {
+        let state = self.state.lock().unwrap();
+        let md = &state.metadata;
+        let mut map = std::collections::HashMap::new();
+        map.insert(
+            "mpris:trackid".to_owned(),
+            Value::new(
+                zbus::zvariant::ObjectPath::try_from(format!("{}/track/{}", OBJECT_PATH, md.track_id))
+                    .unwrap_or_else(|_| zbus::zvariant::ObjectPath::try_from(format!("{}/track/0", OBJECT_PATH)).unwrap())
+                    .to_owned(),
+            ),
+        );
+        map.insert("xesam:title".to_owned(), Value::new(md.title.clone()));
+        map.insert(
+            "xesam:artist".to_owned(),
+            Value::new(vec![md.artist.clone()]),
+        );
+        map.insert("xesam:album".to_owned(), Value::new(md.album.clone()));
+        map.insert("mpris:artUrl".to_owned(), Value::new(md.art_url.clone()));
+        map.insert("mpris:length".to_owned(), Value::new(md.length_us));
+        map
+    }
+
+    #[dbus_interface(property)]
+    fn volume(&self) -> f64 {
+        1.0
+    }
+
+    #[dbus_interface(property)]
+    fn position(&self) -> i64 {
+        0
+    }
+
+    #[dbus_interface(property)]
+    fn can_go_next(&self) -> bool {
+        self.state.lock().unwrap().can_go_next
+    }
+
+    #[dbus_interface(property)]
+    fn can_go_previous(&self) -> bool {
+        self.state.lock().unwrap().can_go_previous
+    }
+
+    #[dbus_interface(property)]
+    fn can_play(&self) -> bool {
+        true
+    }
+
+    #[dbus_interface(property)]
+    fn can_pause(&self) -> bool {
+        true
+    }
+
+    #[dbus_interface(property)]
+    fn can_seek(&self) -> bool {
+        true
+    }
+
+    #[dbus_interface(property)]
+    fn can_control(&self) -> bool {
+        true
+    }
+}
+
+impl Player {
+    fn send(&self, action: Action) {
+        self.sender
+            .send(action)
+            .unwrap_or_else(|e| error!("mpris: failed to send action: {}", e));
+    }
+}
+
+/// Owns the dedicated D-Bus connection used to expose the MPRIS2 interfaces.
+pub(crate) struct MprisServer {
+    connection: zbus::Connection,
+    state: Arc<Mutex<MprisState>>,
+}
+
+impl MprisServer {
+    /// Registers `org.mpris.MediaPlayer2[.Player]` on the session bus.
+    pub(crate) fn new(sender: Sender<Action>) -> fdo::Result<Self> {
+        let state = Arc::new(Mutex::new(MprisState::default()));
+
+        let connection = async_io::block_on(
+            ConnectionBuilder::session()?
+                .name(BUS_NAME)?
+                .serve_at(OBJECT_PATH, MediaPlayer2 { sender: sender.clone() })?
+                .serve_at(
+                    OBJECT_PATH,
+                    Player {
+                        sender,
+                        state: state.clone(),
+                    },
+                )?
+                .build(),
+        )?;
+
+        Ok(MprisServer { connection, state })
+    }
+
+    /// Updates cached metadata/status/can-go-* state for a new track and
+    /// emits `PropertiesChanged` for all of it. `can_go_next`/`can_go_previous`
+    /// should be computed from the actual queue state (repeat mode, position),
+    /// not assumed.
+    pub(crate) fn update(
+        &self,
+        song: &SongInfo,
+        status: PlaybackStatus,
+        can_go_next: bool,
+        can_go_previous: bool,
+    ) {
+        let mut state = self.state.lock().unwrap();
+        state.metadata = MprisMetadata {
+            track_id: song.id.to_string(),
+            title: song.name.clone(),
+            artist: song.singer.clone(),
+            album: song.album.clone(),
+            art_url: song.pic_url.clone(),
+            length_us: (song.duration as i64) * 1000,
+        };
+        state.status = status;
+        state.can_go_next = can_go_next;
+        state.can_go_previous = can_go_previous;
+        drop(state);
+
+        let connection = self.connection.clone();
+        async_io::block_on(async move {
+            let ctxt = SignalContext::new(&connection, OBJECT_PATH).unwrap();
+            let _ = Player::playback_status_changed(&ctxt).await;
+            let _ = Player::metadata_changed(&ctxt).await;
+            let _ = Player::can_go_next_changed(&ctxt).await;
+            let _ = Player::can_go_previous_changed(&ctxt).await;
+        });
+    }
+
+    /// Updates just the playback status (play/pause/stop), leaving the
+    /// current track metadata and can-go-* flags untouched. Used for
+    /// transitions that don't change which track is loaded.
+    pub(crate) fn update_status(&self, status: PlaybackStatus) {
+        let mut state = self.state.lock().unwrap();
+        state.status = status;
+        drop(state);
+
+        let connection = self.connection.clone();
+        async_io::block_on(async move {
+            let ctxt = SignalContext::new(&connection, OBJECT_PATH).unwrap();
+            let _ = Player::playback_status_changed(&ctxt).await;
+        });
+    }
+}
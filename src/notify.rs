@@ -0,0 +1,194 @@
+//
+// notify.rs
+// Copyright (C) 2019 gmg137 <gmg137@live.com>
+// Distributed under terms of the GPLv3 license.
+//
+// Native desktop notifications (org.freedesktop.Notifications) for track
+// changes, falling back to the in-app overlay notice when the service
+// isn't available. Missing capabilities only drop the cover image or the
+// action buttons from the notification.
+//
+use crossbeam_channel::Sender;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashSet;
+use std::hash::{Hash, Hasher};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use zbus::blocking::Connection;
+use zbus::dbus_proxy;
+
+use crate::app::Action;
+use crate::musicapi::model::SongInfo;
+use crate::musicapi::MusicApi;
+
+#[dbus_proxy(
+    interface = "org.freedesktop.Notifications",
+    default_path = "/org/freedesktop/Notifications",
+    default_service = "org.freedesktop.Notifications"
+)]
+trait Notifications {
+    fn notify(
+        &self,
+        app_name: &str,
+        replaces_id: u32,
+        app_icon: &str,
+        summary: &str,
+        body: &str,
+        actions: &[&str],
+        hints: std::collections::HashMap<&str, zbus::zvariant::Value<'_>>,
+        expire_timeout: i32,
+    ) -> zbus::Result<u32>;
+
+    fn get_capabilities(&self) -> zbus::Result<Vec<String>>;
+
+    #[dbus_proxy(signal)]
+    fn action_invoked(&self, id: u32, action_key: String) -> zbus::Result<()>;
+}
+
+/// Downloads `url` into a local cache file keyed by its hash (so repeat
+/// notifications for the same track don't re-download) and returns the
+/// cached file's path. Returns `None` if the URL is empty or the download
+/// fails.
+fn cache_cover(url: &str) -> Option<PathBuf> {
+    if url.is_empty() {
+        return None;
+    }
+
+    let mut hasher = DefaultHasher::new();
+    url.hash(&mut hasher);
+    let cache_dir = glib::get_user_cache_dir().join("netease-cloud-music-gtk/covers");
+    let path = cache_dir.join(format!("{:x}.img", hasher.finish()));
+
+    if path.exists() {
+        return Some(path);
+    }
+
+    std::fs::create_dir_all(&cache_dir)
+        .map_err(|e| error!("notify: failed to create cover cache dir: {}", e))
+        .ok()?;
+
+    let bytes = MusicApi::download(url)
+        .map_err(|e| error!("notify: failed to download cover: {}", e))
+        .ok()?;
+    std::fs::write(&path, bytes)
+        .map_err(|e| error!("notify: failed to cache cover: {}", e))
+        .ok()?;
+
+    Some(path)
+}
+
+/// Capabilities of the running notification server that we care about.
+#[derive(Debug, Default)]
+struct Capabilities {
+    body_images: bool,
+    actions: bool,
+}
+
+/// Drives the freedesktop notification service. Falls back to the in-app
+/// `InAppNotification` overlay (via `Action::ShowNotice`) if the service
+/// can't be reached at all.
+pub(crate) struct Notifier {
+    connection: Connection,
+    capabilities: Capabilities,
+    last_id: Mutex<u32>,
+}
+
+impl Notifier {
+    pub(crate) fn new(sender: Sender<Action>) -> zbus::Result<Self> {
+        let connection = Connection::session()?;
+        let proxy = NotificationsProxyBlocking::new(&connection)?;
+        let caps: HashSet<String> = proxy.get_capabilities()?.into_iter().collect();
+        let capabilities = Capabilities {
+            body_images: caps.contains("body-images") || caps.contains("icon-static"),
+            actions: caps.contains("actions"),
+        };
+
+        let notifier = Notifier {
+            connection,
+            capabilities,
+            last_id: Mutex::new(0),
+        };
+
+        notifier.listen_for_actions(sender);
+        Ok(notifier)
+    }
+
+    /// Shows (or replaces) the notification for the currently playing song.
+    /// Returns `Err` if the service call failed, so the caller can fall
+    /// back to `Action::ShowNotice`. Downloads and caches the cover locally
+    /// first, since notification daemons resolve `image-path` as a local
+    /// file path (or `file://` URI), not a remote HTTP URL.
+    pub(crate) fn notify_track_change(&self, song: &SongInfo) -> zbus::Result<()> {
+        let proxy = NotificationsProxyBlocking::new(&self.connection)?;
+
+        let mut hints = std::collections::HashMap::new();
+        if self.capabilities.body_images {
+            if let Some(cover_path) = cache_cover(&song.pic_url) {
+                hints.insert(
+                    "image-path",
+                    zbus::zvariant::Value::new(format!("file://{}", cover_path.display())),
+                );
+            }
+        }
+
+        let actions: &[&str] = if self.capabilities.actions {
+            &["previous", "Previous", "play-pause", "Play/Pause", "next", "Next"]
+        } else {
+            &[]
+        };
+
+        let replaces_id = *self.last_id.lock().unwrap();
+        let id = proxy.notify(
+            "netease-cloud-music-gtk",
+            replaces_id,
+            "netease-cloud-music-gtk",
+            &song.name,
+            &song.singer,
+            actions,
+            hints,
+            5000,
+        )?;
+        *self.last_id.lock().unwrap() = id;
+
+        Ok(())
+    }
+
+    /// Spawns a thread that forwards `ActionInvoked` signals back onto the
+    /// `Action` channel so clicking a notification button controls playback.
+    fn listen_for_actions(&self, sender: Sender<Action>) {
+        let connection = self.connection.clone();
+        std::thread::spawn(move || {
+            let proxy = match NotificationsProxyBlocking::new(&connection) {
+                Ok(p) => p,
+                Err(e) => {
+                    error!("notify: failed to watch ActionInvoked: {}", e);
+                    return;
+                }
+            };
+            let mut stream = match proxy.receive_action_invoked() {
+                Ok(s) => s,
+                Err(e) => {
+                    error!("notify: failed to subscribe to ActionInvoked: {}", e);
+                    return;
+                }
+            };
+            for signal in &mut stream {
+                let args = match signal.args() {
+                    Ok(a) => a,
+                    Err(_) => continue,
+                };
+                let action = match args.action_key.as_str() {
+                    "previous" => Some(Action::PlayerPrevious),
+                    "play-pause" => Some(Action::PlayerPlayPause),
+                    "next" => Some(Action::PlayerNext),
+                    _ => None,
+                };
+                if let Some(action) = action {
+                    sender
+                        .send(action)
+                        .unwrap_or_else(|e| error!("notify: failed to forward action: {}", e));
+                }
+            }
+        });
+    }
+}
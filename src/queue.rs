@@ -0,0 +1,360 @@
+//
+// queue.rs
+// Copyright (C) 2019 gmg137 <gmg137@live.com>
+// Distributed under terms of the GPLv3 license.
+//
+// Persistent playback queue: the current track list, the active index,
+// a resume position, and the repeat/shuffle modes. Serialized to disk on
+// every change so the app can resume the track the user left off on.
+//
+use crate::musicapi::model::SongInfo;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) enum RepeatMode {
+    Off,
+    All,
+    One,
+}
+
+impl Default for RepeatMode {
+    fn default() -> Self {
+        RepeatMode::Off
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) enum ShuffleMode {
+    Off,
+    On,
+}
+
+impl Default for ShuffleMode {
+    fn default() -> Self {
+        ShuffleMode::Off
+    }
+}
+
+/// The serialized playback queue: songs, the shuffled/linear play order,
+/// where we are in that order, the repeat/shuffle modes, and a resume
+/// position in milliseconds (restoring seeks to it when it is non-zero).
+/// The position is reset to 0 on every track change; playback progress is
+/// not recorded into it, so a restored track starts from the beginning.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub(crate) struct PlayQueue {
+    songs: Vec<SongInfo>,
+    /// A permutation of `0..songs.len()`. Linear (`0, 1, 2, ...`) when
+    /// shuffle is off; randomized once per shuffle toggle otherwise, so
+    /// Previous retraces the same path Next took.
+    order: Vec<usize>,
+    /// Index into `order`, not into `songs`.
+    position: usize,
+    pub(crate) last_position_ms: u64,
+    pub(crate) repeat: RepeatMode,
+    pub(crate) shuffle: ShuffleMode,
+}
+
+impl PlayQueue {
+    pub(crate) fn new(songs: Vec<SongInfo>) -> Self {
+        let order: Vec<usize> = (0..songs.len()).collect();
+        PlayQueue {
+            songs,
+            order,
+            position: 0,
+            last_position_ms: 0,
+            repeat: RepeatMode::Off,
+            shuffle: ShuffleMode::Off,
+        }
+    }
+
+    pub(crate) fn current(&self) -> Option<&SongInfo> {
+        self.order.get(self.position).and_then(|&i| self.songs.get(i))
+    }
+
+    /// Whether `advance()` would actually move to a (possibly repeated)
+    /// track, for `CanGoNext`-style UI/MPRIS state.
+    pub(crate) fn can_advance(&self) -> bool {
+        if self.songs.is_empty() {
+            return false;
+        }
+        match self.repeat {
+            RepeatMode::One | RepeatMode::All => true,
+            RepeatMode::Off => self.position + 1 < self.order.len(),
+        }
+    }
+
+    /// Whether `retreat()` would actually move to a (possibly repeated)
+    /// track, for `CanGoPrevious`-style UI/MPRIS state.
+    pub(crate) fn can_retreat(&self) -> bool {
+        if self.songs.is_empty() {
+            return false;
+        }
+        match self.repeat {
+            RepeatMode::One | RepeatMode::All => true,
+            RepeatMode::Off => self.position > 0,
+        }
+    }
+
+    /// Advances to the next track per the active repeat/shuffle mode.
+    /// Returns `None` when playback should stop (end of queue, Repeat::Off).
+    pub(crate) fn advance(&mut self) -> Option<&SongInfo> {
+        if self.songs.is_empty() {
+            return None;
+        }
+
+        if self.repeat == RepeatMode::One {
+            return self.current();
+        }
+
+        if self.position + 1 < self.order.len() {
+            self.position += 1;
+        } else if self.repeat == RepeatMode::All {
+            self.position = 0;
+        } else {
+            return None;
+        }
+
+        self.last_position_ms = 0;
+        self.current()
+    }
+
+    /// Moves back to the previous track in the established play order
+    /// (the same shuffled order Next advanced through, so Previous is
+    /// coherent rather than re-randomized).
+    pub(crate) fn retreat(&mut self) -> Option<&SongInfo> {
+        if self.songs.is_empty() {
+            return None;
+        }
+
+        if self.repeat == RepeatMode::One {
+            return self.current();
+        }
+
+        if self.position > 0 {
+            self.position -= 1;
+        } else if self.repeat == RepeatMode::All {
+            self.position = self.order.len() - 1;
+        } else {
+            return self.current();
+        }
+
+        self.last_position_ms = 0;
+        self.current()
+    }
+
+    /// Regenerates the play order. Shuffle produces one fixed permutation
+    /// (not a fresh random pick per step) so Previous stays coherent;
+    /// turning shuffle off restores linear order.
+    pub(crate) fn set_shuffle(&mut self, mode: ShuffleMode) {
+        self.shuffle = mode;
+        let current_song = self.current().map(|s| s.id);
+
+        self.order = (0..self.songs.len()).collect();
+        if mode == ShuffleMode::On {
+            shuffle_indices(&mut self.order);
+        }
+
+        self.position = current_song
+            .and_then(|id| {
+                self.order
+                    .iter()
+                    .position(|&i| self.songs.get(i).map(|s| s.id) == Some(id))
+            })
+            .unwrap_or(0);
+    }
+
+    pub(crate) fn set_repeat(&mut self, mode: RepeatMode) {
+        self.repeat = mode;
+    }
+
+    fn state_path() -> PathBuf {
+        glib::get_user_config_dir()
+            .join("netease-cloud-music-gtk")
+            .join("queue.json")
+    }
+
+    pub(crate) fn load() -> Option<Self> {
+        let raw = fs::read_to_string(Self::state_path()).ok()?;
+        serde_json::from_str(&raw).ok()
+    }
+
+    pub(crate) fn save(&self) {
+        let path = Self::state_path();
+        if let Some(parent) = path.parent() {
+            if let Err(e) = fs::create_dir_all(parent) {
+                error!("queue: failed to create config dir: {}", e);
+                return;
+            }
+        }
+        match serde_json::to_string(self) {
+            Ok(json) => {
+                if let Err(e) = fs::write(&path, json) {
+                    error!("queue: failed to persist play queue: {}", e);
+                }
+            }
+            Err(e) => error!("queue: failed to serialize play queue: {}", e),
+        }
+    }
+}
+
+/// Fisher-Yates shuffle using a simple xorshift PRNG seeded from the
+/// system clock, avoiding a dependency on the `rand` crate for a single
+/// one-off permutation.
+fn shuffle_indices(order: &mut [usize]) {
+    let mut state = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or(0x2545F4914F6CDD1D)
+        | 1;
+
+    let mut next_rand = move || {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        state
+    };
+
+    for i in (1..order.len()).rev() {
+        let j = (next_rand() as usize) % (i + 1);
+        order.swap(i, j);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn song(id: i64) -> SongInfo {
+        SongInfo {
+            id,
+            ..Default::default()
+        }
+    }
+
+    fn queue(ids: &[i64]) -> PlayQueue {
+        PlayQueue::new(ids.iter().copied().map(song).collect())
+    }
+
+    #[test]
+    fn advance_steps_linearly_and_stops_at_the_end_by_default() {
+        let mut q = queue(&[1, 2, 3]);
+        assert_eq!(q.current().map(|s| s.id), Some(1));
+        assert_eq!(q.advance().map(|s| s.id), Some(2));
+        assert_eq!(q.advance().map(|s| s.id), Some(3));
+        assert!(q.advance().is_none());
+        // Staying put at the last track once exhausted, not wrapping.
+        assert_eq!(q.current().map(|s| s.id), Some(3));
+    }
+
+    #[test]
+    fn repeat_all_wraps_around_in_both_directions() {
+        let mut q = queue(&[1, 2, 3]);
+        q.set_repeat(RepeatMode::All);
+
+        q.advance();
+        q.advance();
+        assert_eq!(q.current().map(|s| s.id), Some(3));
+        assert_eq!(q.advance().map(|s| s.id), Some(1));
+
+        assert_eq!(q.retreat().map(|s| s.id), Some(3));
+    }
+
+    #[test]
+    fn repeat_one_replays_the_same_track_without_advancing() {
+        let mut q = queue(&[1, 2, 3]);
+        q.set_repeat(RepeatMode::One);
+
+        assert_eq!(q.advance().map(|s| s.id), Some(1));
+        assert_eq!(q.advance().map(|s| s.id), Some(1));
+        assert_eq!(q.retreat().map(|s| s.id), Some(1));
+    }
+
+    #[test]
+    fn retreat_without_repeat_all_stays_on_the_first_track() {
+        let mut q = queue(&[1, 2, 3]);
+        assert_eq!(q.retreat().map(|s| s.id), Some(1));
+    }
+
+    #[test]
+    fn shuffle_then_previous_retraces_the_same_order_next_took() {
+        let mut q = queue(&[1, 2, 3, 4, 5]);
+        // The current track may land anywhere in the shuffled order, so wrap
+        // around instead of running off the end.
+        q.set_repeat(RepeatMode::All);
+        q.set_shuffle(ShuffleMode::On);
+
+        let first = q.current().map(|s| s.id).unwrap();
+        let second = q.advance().map(|s| s.id).unwrap();
+        let third = q.advance().map(|s| s.id).unwrap();
+
+        assert_eq!(q.retreat().map(|s| s.id), Some(second));
+        assert_eq!(q.retreat().map(|s| s.id), Some(first));
+        // Sanity: the three tracks visited are distinct songs.
+        let mut ids = vec![first, second, third];
+        ids.sort_unstable();
+        ids.dedup();
+        assert_eq!(ids.len(), 3);
+    }
+
+    #[test]
+    fn toggling_shuffle_preserves_the_current_track() {
+        let mut q = queue(&[1, 2, 3, 4, 5]);
+        q.advance();
+        let current = q.current().map(|s| s.id);
+
+        q.set_shuffle(ShuffleMode::On);
+        assert_eq!(q.current().map(|s| s.id), current);
+
+        q.set_shuffle(ShuffleMode::Off);
+        assert_eq!(q.current().map(|s| s.id), current);
+    }
+
+    #[test]
+    fn empty_queue_never_advances_or_retreats() {
+        let mut q = queue(&[]);
+        assert!(q.current().is_none());
+        assert!(q.advance().is_none());
+        assert!(q.retreat().is_none());
+    }
+
+    #[test]
+    fn can_advance_and_retreat_reflect_position_under_repeat_off() {
+        let mut q = queue(&[1, 2, 3]);
+        assert!(!q.can_retreat());
+        assert!(q.can_advance());
+
+        q.advance();
+        assert!(q.can_retreat());
+        assert!(q.can_advance());
+
+        q.advance();
+        assert!(q.can_retreat());
+        assert!(!q.can_advance());
+    }
+
+    #[test]
+    fn can_advance_and_retreat_are_always_true_under_repeat_all_or_one() {
+        let mut q = queue(&[1, 2, 3]);
+        q.set_repeat(RepeatMode::All);
+        assert!(q.can_advance());
+        assert!(q.can_retreat());
+
+        q.advance();
+        q.advance();
+        assert!(q.can_advance());
+        assert!(q.can_retreat());
+
+        q.set_repeat(RepeatMode::One);
+        assert!(q.can_advance());
+        assert!(q.can_retreat());
+    }
+
+    #[test]
+    fn empty_queue_can_never_advance_or_retreat() {
+        let q = queue(&[]);
+        assert!(!q.can_advance());
+        assert!(!q.can_retreat());
+    }
+}
@@ -0,0 +1,82 @@
+//
+// lyrics_view.rs
+// Copyright (C) 2019 gmg137 <gmg137@live.com>
+// Distributed under terms of the GPLv3 license.
+//
+// The lyrics panel itself: a `GtkListBox` of lines inside a scrolled
+// window. `update_lyrics` rebuilds the rows from a freshly fetched LRC;
+// `tick` binary-searches the active line on every playback position
+// update and keeps it selected and scrolled into view, so the panel
+// follows along with the song rather than just displaying static text.
+//
+use crate::lyrics::current_line;
+use gtk::prelude::*;
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::Duration;
+
+pub(crate) struct LyricsView {
+    list_box: gtk::ListBox,
+    lines: RefCell<Vec<(Duration, String)>>,
+    current: RefCell<Option<usize>>,
+}
+
+impl LyricsView {
+    pub(crate) fn new(builder: &gtk::Builder) -> Rc<Self> {
+        let list_box: gtk::ListBox = builder
+            .get_object("lyrics_list_box")
+            .expect("Couldn't get lyrics_list_box");
+
+        Rc::new(LyricsView {
+            list_box,
+            lines: RefCell::new(Vec::new()),
+            current: RefCell::new(None),
+        })
+    }
+
+    /// Replaces the displayed lyrics with a freshly fetched LRC (millisecond
+    /// timestamps, as delivered by `Action::RefreshLyrics`) and rebuilds the
+    /// row list.
+    pub(crate) fn update_lyrics(&self, lines: Vec<(u64, String)>) {
+        for child in self.list_box.get_children() {
+            self.list_box.remove(&child);
+        }
+
+        let lines: Vec<(Duration, String)> = lines
+            .into_iter()
+            .map(|(ms, text)| (Duration::from_millis(ms), text))
+            .collect();
+
+        for (_, text) in &lines {
+            let label = gtk::Label::new(Some(text.as_str()));
+            label.set_halign(gtk::Align::Center);
+            self.list_box.insert(&label, -1);
+        }
+        self.list_box.show_all();
+
+        *self.lines.borrow_mut() = lines;
+        *self.current.borrow_mut() = None;
+    }
+
+    /// Called on every playback position tick. Binary-searches the active
+    /// line and, only when it actually changed, highlights the new row and
+    /// scrolls it into view.
+    pub(crate) fn tick(&self, position: Duration) {
+        let lines = self.lines.borrow();
+        let index = match current_line(&lines, position) {
+            Some(i) => i,
+            None => return,
+        };
+        drop(lines);
+
+        if *self.current.borrow() == Some(index) {
+            return;
+        }
+        *self.current.borrow_mut() = Some(index);
+
+        if let Some(row) = self.list_box.get_row_at_index(index as i32) {
+            self.list_box.select_row(Some(&row));
+            row.grab_focus();
+        }
+    }
+}
@@ -0,0 +1,8 @@
+//
+// mod.rs
+// Copyright (C) 2019 gmg137 <gmg137@live.com>
+// Distributed under terms of the GPLv3 license.
+//
+
+pub(crate) mod lyrics_view;
+pub(crate) mod player;
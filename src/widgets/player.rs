@@ -0,0 +1,190 @@
+//
+// player.rs
+// Copyright (C) 2019 gmg137 <gmg137@live.com>
+// Distributed under terms of the GPLv3 license.
+//
+// Wraps a single GStreamer `playbin` pipeline: the small surface the rest
+// of the app drives playback through. Bus messages (EOS, pipeline errors)
+// are translated back into `Action`s so the rest of the app never has to
+// poll pipeline state directly.
+//
+use crate::app::Action;
+use crate::mpris::PlaybackStatus;
+use crate::musicapi::model::SongInfo;
+use crate::utils::PlayerTypes;
+use crossbeam_channel::Sender;
+use gst::prelude::*;
+use gtk::Builder;
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+
+#[derive(Clone)]
+pub(crate) struct PlayerWrapper {
+    playbin: gst::Element,
+    sender: Sender<Action>,
+    current: Rc<RefCell<Option<SongInfo>>>,
+}
+
+impl PlayerWrapper {
+    pub(crate) fn new(_builder: &Builder, sender: &Sender<Action>, _data: Arc<Mutex<u8>>) -> Self {
+        if let Err(e) = gst::init() {
+            error!("player: failed to initialize GStreamer: {}", e);
+        }
+
+        let playbin = gst::ElementFactory::make("playbin", Some("player"))
+            .expect("playbin element missing; is gstreamer-plugins-base installed?");
+
+        let player = PlayerWrapper {
+            playbin,
+            sender: sender.clone(),
+            current: Rc::new(RefCell::new(None)),
+        };
+
+        player.watch_bus();
+        player
+    }
+
+    /// Watches the pipeline bus for end-of-stream and error messages,
+    /// translating them into `Action`s so natural track completion and
+    /// playback failures are both handled through the normal queue/fallback
+    /// path instead of leaving the UI stuck on a track that will never play.
+    fn watch_bus(&self) {
+        let bus = match self.playbin.get_bus() {
+            Some(bus) => bus,
+            None => {
+                error!("player: playbin has no bus, can't watch for errors/EOS");
+                return;
+            }
+        };
+
+        let sender = self.sender.clone();
+        let current = self.current.clone();
+        let watch_result = bus.add_watch_local(move |_, msg| {
+            use gst::MessageView;
+            match msg.view() {
+                MessageView::Eos(..) => {
+                    sender
+                        .send(Action::PlayerNext)
+                        .unwrap_or_else(|e| error!("player: failed to deliver PlayerNext on EOS: {}", e));
+                }
+                MessageView::Error(err) => {
+                    error!(
+                        "player: pipeline error from {:?}: {} ({:?})",
+                        err.get_src().map(|s| s.get_path_string()),
+                        err.get_error(),
+                        err.get_debug()
+                    );
+                    if let Some(song) = current.borrow().clone() {
+                        sender
+                            .send(Action::PlayerError(song))
+                            .unwrap_or_else(|e| error!("player: failed to deliver PlayerError: {}", e));
+                    }
+                }
+                _ => (),
+            }
+            glib::Continue(true)
+        });
+        if let Err(e) = watch_result {
+            error!("player: failed to attach bus watch: {}", e);
+        }
+    }
+
+    /// Skips ahead without waiting for the current track to finish (used by
+    /// the FM "dislike" flow).
+    pub(crate) fn forward(&self) {
+        self.sender
+            .send(Action::PlayerNext)
+            .unwrap_or_else(|e| error!("player: failed to deliver PlayerNext: {}", e));
+    }
+
+    /// Primes the player with `song` ahead of actual playback starting
+    /// (used by the FM flow, which fetches the next track before the user
+    /// gets to it).
+    pub(crate) fn initialize_player(&self, song: SongInfo, _kind: PlayerTypes) {
+        *self.current.borrow_mut() = Some(song);
+    }
+
+    /// Loads `url` into the pipeline and starts playing `song`.
+    pub(crate) fn player(&self, song: SongInfo, url: String) {
+        let _ = self.playbin.set_state(gst::State::Null);
+        if let Err(e) = self.playbin.set_property("uri", &url) {
+            error!("player: failed to set playback uri: {}", e);
+        }
+        *self.current.borrow_mut() = Some(song);
+        if let Err(e) = self.playbin.set_state(gst::State::Playing) {
+            error!("player: failed to start playback: {:?}", e);
+        }
+    }
+
+    /// Toggles between play and pause and returns the status it switched
+    /// to, so callers can push it straight into MPRIS without re-querying
+    /// pipeline state. A failed state change is logged and the requested
+    /// status is still returned.
+    pub(crate) fn play_pause(&self) -> PlaybackStatus {
+        let (_, current_state, _) = self.playbin.get_state(gst::ClockTime::from_mseconds(100));
+        let target = if current_state == gst::State::Playing {
+            gst::State::Paused
+        } else {
+            gst::State::Playing
+        };
+        if let Err(e) = self.playbin.set_state(target) {
+            error!("player: failed to change playback state: {:?}", e);
+        }
+
+        match target {
+            gst::State::Playing => PlaybackStatus::Playing,
+            _ => PlaybackStatus::Paused,
+        }
+    }
+
+    /// Stops playback outright (distinct from pause: drops the pipeline to
+    /// `Null` so the next `player()` call starts clean) and returns the
+    /// resulting status.
+    pub(crate) fn stop(&self) -> PlaybackStatus {
+        if let Err(e) = self.playbin.set_state(gst::State::Null) {
+            error!("player: failed to stop playback: {:?}", e);
+        }
+        PlaybackStatus::Stopped
+    }
+
+    /// Seeks `offset_us` microseconds relative to the current position
+    /// (MPRIS `Seek`); negative values rewind.
+    pub(crate) fn seek_relative(&self, offset_us: i64) {
+        let position = match self.playbin.query_position::<gst::ClockTime>() {
+            Some(pos) => pos,
+            None => {
+                warn!("player: can't seek relative, no known position");
+                return;
+            }
+        };
+        let position_ns = position.nseconds().unwrap_or(0) as i64;
+        let target_ns = (position_ns + offset_us * 1000).max(0) as u64;
+        self.seek_to(gst::ClockTime::from_nseconds(target_ns));
+    }
+
+    /// Seeks to an absolute position (MPRIS `SetPosition`). `track_id` is
+    /// accepted for signature parity with the D-Bus method but otherwise
+    /// unused, since this player only ever has one track loaded at a time.
+    pub(crate) fn set_position(&self, _track_id: String, position_us: i64) {
+        self.seek_to(gst::ClockTime::from_useconds(position_us.max(0) as u64));
+    }
+
+    /// Current playback position, for driving the lyrics panel's scroll
+    /// position. `None` when nothing is loaded or playing.
+    pub(crate) fn position(&self) -> Option<std::time::Duration> {
+        self.playbin
+            .query_position::<gst::ClockTime>()
+            .and_then(|pos| pos.nseconds())
+            .map(std::time::Duration::from_nanos)
+    }
+
+    fn seek_to(&self, position: gst::ClockTime) {
+        if !self
+            .playbin
+            .seek_simple(gst::SeekFlags::FLUSH | gst::SeekFlags::KEY_UNIT, position)
+        {
+            warn!("player: seek to {} failed", position);
+        }
+    }
+}